@@ -0,0 +1,450 @@
+use clap::Args;
+use env_logger::Logger;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct LogOpts {
+    /// Additionally write logs to this file.
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    /// Rotate the log file once it would grow beyond this many bytes.
+    #[clap(
+        long,
+        default_value = "10000000",
+        requires = "log_file",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    log_file_max_bytes: u64,
+
+    /// Number of rotated log files (`<log-file>.1`, `<log-file>.2`, ...) to keep.
+    #[clap(long, default_value = "5", requires = "log_file")]
+    log_file_keep: usize,
+
+    /// Don't log to stderr.
+    #[clap(long)]
+    quiet: bool,
+}
+
+/// Initialize the global logger, filtered via `RUST_LOG` as before.
+pub fn init(opts: &LogOpts) -> Result<(), Box<dyn Error>> {
+    let stderr = (!opts.quiet).then(|| env_logger::Builder::from_default_env().build());
+    let file = match &opts.log_file {
+        Some(path) => {
+            let file =
+                RotatingFile::open(path.clone(), opts.log_file_max_bytes, opts.log_file_keep)?;
+            Some(
+                env_logger::Builder::from_default_env()
+                    .target(env_logger::Target::Pipe(Box::new(file)))
+                    .build(),
+            )
+        }
+        None => None,
+    };
+
+    let logger = Tee { stderr, file };
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger))?;
+
+    Ok(())
+}
+
+/// Logs every record to stderr and to the log file, each being a separate
+/// [`Logger`]. This keeps stderr colored, keeps escape codes out of the file
+/// and, as env_logger ignores write errors, a failing sink (e.g. a closed
+/// stderr pipe) doesn't keep records from the other.
+struct Tee {
+    stderr: Option<Logger>,
+    file: Option<Logger>,
+}
+
+impl Tee {
+    fn sinks(&self) -> impl Iterator<Item = &Logger> {
+        self.stderr.iter().chain(self.file.iter())
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.sinks()
+            .map(|logger| logger.filter())
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+}
+
+impl Log for Tee {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.sinks().any(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        for logger in self.sinks() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        for logger in self.sinks() {
+            logger.flush();
+        }
+    }
+}
+
+/// Append-only file that is moved to `<path>.1` once it reaches `max_bytes`,
+/// shifting older files up to `<path>.<keep>` and dropping anything beyond.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = open_append(&path)?;
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            len,
+            max_bytes,
+            keep,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate_if_full(&mut self, next: usize) -> io::Result<()> {
+        if self.len > 0 && self.len + next as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        match self.shift_rotated_files() {
+            // The live file has been moved to `<path>.1`, or no rotated files
+            // are kept and it is truncated.
+            Ok(()) => self.file = File::create(&self.path)?,
+            // The live file may still be in place. Keep appending to it rather
+            // than losing its records, and only retry after another
+            // `max_bytes` instead of on every write.
+            Err(e) => {
+                // Not logged via `log`, as that would recurse into this writer.
+                let _ = writeln!(
+                    io::stderr(),
+                    "Failed to rotate log file {}, continuing to append to it: {}",
+                    self.path.display(),
+                    e
+                );
+                self.file = open_append(&self.path)?;
+            }
+        }
+        self.len = 0;
+
+        Ok(())
+    }
+
+    fn shift_rotated_files(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.keep == 0 {
+            return Ok(());
+        }
+
+        ignore_not_found(fs::remove_file(self.rotated_path(self.keep)))?;
+        for n in (1..self.keep).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+        // The live file may have been moved or deleted from underneath us.
+        ignore_not_found(fs::rename(&self.path, self.rotated_path(1)))?;
+
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rotate_if_full(buf.len())?;
+
+        let n = self.file.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    // env_logger writes each record with a single `write_all`. Decide on
+    // rotation once per call, so a record is never split across two files.
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.rotate_if_full(buf.len())?;
+
+        self.file.write_all(buf)?;
+        self.len += buf.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::sync::{Arc, Mutex};
+
+    /// Temporary directory, removed again on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "libp2p-workshop-node-logging-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn log(&self) -> PathBuf {
+            self.0.join("node.log")
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn rotated(path: &Path, n: usize) -> PathBuf {
+        let mut path = path.to_path_buf().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rotates_in_order_and_caps_at_keep() {
+        let dir = TempDir::new("order");
+        let path = dir.log();
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        file.write_all(b"aaaaaaaaaa").unwrap();
+        assert!(
+            !rotated(&path, 1).exists(),
+            "a full file is only rotated on the next write"
+        );
+
+        file.write_all(b"bbbbbbbbbb").unwrap();
+        assert_eq!(read(&path), "bbbbbbbbbb");
+        assert_eq!(read(&rotated(&path, 1)), "aaaaaaaaaa");
+
+        file.write_all(b"cccccccccc").unwrap();
+        assert_eq!(read(&path), "cccccccccc");
+        assert_eq!(read(&rotated(&path, 1)), "bbbbbbbbbb");
+        assert_eq!(read(&rotated(&path, 2)), "aaaaaaaaaa");
+
+        file.write_all(b"dddddddddd").unwrap();
+        assert_eq!(read(&path), "dddddddddd");
+        assert_eq!(read(&rotated(&path, 1)), "cccccccccc");
+        assert_eq!(read(&rotated(&path, 2)), "bbbbbbbbbb");
+        assert!(!rotated(&path, 3).exists());
+    }
+
+    #[test]
+    fn keep_zero_truncates() {
+        let dir = TempDir::new("keep-zero");
+        let path = dir.log();
+        let mut file = RotatingFile::open(path.clone(), 10, 0).unwrap();
+
+        file.write_all(b"aaaaaaaaaa").unwrap();
+        file.write_all(b"bbbbb").unwrap();
+        file.write_all(b"ccccc").unwrap();
+
+        assert_eq!(read(&path), "bbbbbccccc");
+        assert!(!rotated(&path, 1).exists());
+    }
+
+    #[test]
+    fn reopening_continues_from_existing_length() {
+        let dir = TempDir::new("reopen");
+        let path = dir.log();
+        fs::write(&path, b"aaaaa").unwrap();
+
+        let mut file = RotatingFile::open(path.clone(), 10, 1).unwrap();
+        file.write_all(b"bbb").unwrap();
+        assert_eq!(read(&path), "aaaaabbb");
+        assert!(!rotated(&path, 1).exists());
+        drop(file);
+
+        let mut file = RotatingFile::open(path.clone(), 10, 1).unwrap();
+        file.write_all(b"ccc").unwrap();
+        assert_eq!(read(&path), "ccc");
+        assert_eq!(read(&rotated(&path, 1)), "aaaaabbb");
+    }
+
+    #[test]
+    fn survives_live_file_being_removed() {
+        let dir = TempDir::new("removed");
+        let path = dir.log();
+        let mut file = RotatingFile::open(path.clone(), 10, 1).unwrap();
+
+        file.write_all(b"aaaaaaaaaa").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        file.write_all(b"bbbbbbbbbb").unwrap();
+        assert_eq!(read(&path), "bbbbbbbbbb");
+
+        file.write_all(b"ccccc").unwrap();
+        assert_eq!(read(&path), "ccccc");
+        assert_eq!(read(&rotated(&path, 1)), "bbbbbbbbbb");
+    }
+
+    #[test]
+    fn failed_rotation_keeps_appending() {
+        let dir = TempDir::new("failed");
+        let path = dir.log();
+        // A non-empty directory in place of `<path>.1` can't be removed.
+        fs::create_dir_all(rotated(&path, 1).join("blocker")).unwrap();
+        let mut file = RotatingFile::open(path.clone(), 10, 1).unwrap();
+
+        file.write_all(b"IMPORTANT!").unwrap();
+        file.write_all(b"next").unwrap();
+        assert_eq!(read(&path), "IMPORTANT!next");
+
+        // The next attempt only happens after another `max_bytes`.
+        file.write_all(b"more").unwrap();
+        assert_eq!(read(&path), "IMPORTANT!nextmore");
+    }
+
+    #[test]
+    fn record_is_not_split_across_files() {
+        let dir = TempDir::new("split");
+        let path = dir.log();
+        let mut file = RotatingFile::open(path.clone(), 10, 1).unwrap();
+
+        file.write_all(b"aaaaaaaa").unwrap();
+        file.write_all(b"bbbbbbbb").unwrap();
+        assert_eq!(read(&path), "bbbbbbbb");
+        assert_eq!(read(&rotated(&path, 1)), "aaaaaaaa");
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+    }
+
+    fn pipe_logger(writer: impl Write + Send + 'static) -> Logger {
+        env_logger::Builder::new()
+            .filter_level(LevelFilter::Info)
+            .target(env_logger::Target::Pipe(Box::new(writer)))
+            .build()
+    }
+
+    fn log_messages(tee: &Tee) {
+        for message in ["first", "second"] {
+            tee.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Info)
+                    .target("test")
+                    .build(),
+            );
+        }
+        tee.flush();
+    }
+
+    #[test]
+    fn tee_writes_to_both_sinks() {
+        let stderr = Buffer::default();
+        let file = Buffer::default();
+        let tee = Tee {
+            stderr: Some(pipe_logger(stderr.clone())),
+            file: Some(pipe_logger(file.clone())),
+        };
+
+        log_messages(&tee);
+
+        for sink in [stderr, file] {
+            let contents = sink.contents();
+            assert!(contents.contains("first") && contents.contains("second"));
+        }
+    }
+
+    #[test]
+    fn tee_continues_after_a_sink_fails() {
+        let file = Buffer::default();
+        let tee = Tee {
+            stderr: Some(pipe_logger(Broken)),
+            file: Some(pipe_logger(file.clone())),
+        };
+        log_messages(&tee);
+        let contents = file.contents();
+        assert!(contents.contains("first") && contents.contains("second"));
+
+        let stderr = Buffer::default();
+        let tee = Tee {
+            stderr: Some(pipe_logger(stderr.clone())),
+            file: Some(pipe_logger(Broken)),
+        };
+        log_messages(&tee);
+        let contents = stderr.contents();
+        assert!(contents.contains("first") && contents.contains("second"));
+    }
+}
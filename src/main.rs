@@ -1,14 +1,19 @@
 use clap::Parser;
 use std::error::Error;
 
+mod logging;
+
 #[derive(Debug, Parser)]
 #[clap(name = "libp2p-workshop-node")]
-struct Opts {}
+struct Opts {
+    #[clap(flatten)]
+    log: logging::LogOpts,
+}
 
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-    let _opts = Opts::parse();
+    let opts = Opts::parse();
+    logging::init(&opts.log)?;
 
     println!("Hello, world!");
 